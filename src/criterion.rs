@@ -0,0 +1,51 @@
+use std::cmp::Ordering;
+
+use crate::{DocumentId, FastMap4};
+
+/// Per-document ranking signals computed once per search and consulted by every
+/// [`Criterion`], so criteria stay cheap comparators instead of re-querying the index.
+#[derive(Default, Clone)]
+pub struct CriterionScores {
+    /// The smallest edit distance, across every query word that only matched fuzzily, that a
+    /// fuzzy-matched word needed to reach this document.
+    pub typo: FastMap4<DocumentId, u32>,
+    /// The smallest position window a query phrase matched within, for documents reached
+    /// through a phrase or proximity query.
+    pub proximity: FastMap4<DocumentId, u32>,
+}
+
+/// A ranking signal used to order two documents that otherwise matched a search query
+/// equally well. Criteria are tried in order; the first one returning anything other than
+/// [`Ordering::Equal`] for a given pair decides it, the rest are never consulted.
+pub trait Criterion {
+    fn evaluate(&self, scores: &CriterionScores, lhs: DocumentId, rhs: DocumentId) -> Ordering;
+}
+
+/// Prefers documents that needed fewer (or no) typos to match the query.
+pub struct Typo;
+
+impl Criterion for Typo {
+    fn evaluate(&self, scores: &CriterionScores, lhs: DocumentId, rhs: DocumentId) -> Ordering {
+        let lhs_typos = scores.typo.get(&lhs).copied().unwrap_or(0);
+        let rhs_typos = scores.typo.get(&rhs).copied().unwrap_or(0);
+        lhs_typos.cmp(&rhs_typos)
+    }
+}
+
+/// Prefers documents where the query words (from a phrase or proximity match) occur
+/// closer together.
+pub struct Proximity;
+
+impl Criterion for Proximity {
+    fn evaluate(&self, scores: &CriterionScores, lhs: DocumentId, rhs: DocumentId) -> Ordering {
+        let lhs_proximity = scores.proximity.get(&lhs).copied().unwrap_or(u32::MAX);
+        let rhs_proximity = scores.proximity.get(&rhs).copied().unwrap_or(u32::MAX);
+        lhs_proximity.cmp(&rhs_proximity)
+    }
+}
+
+/// The criteria applied, in order, to rank a [`Search`](crate::Search)'s matching
+/// documents: fewer typos first, then the tightest word proximity.
+pub fn default_criteria() -> Vec<Box<dyn Criterion>> {
+    vec![Box::new(Typo), Box::new(Proximity)]
+}
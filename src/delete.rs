@@ -0,0 +1,177 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use fst::Streamer;
+use heed::types::{ByteSlice, Str};
+use roaring::RoaringBitmap;
+
+use crate::heed_codec::{RoaringBitmapCodec, StrBEU32Codec};
+use crate::{BEU32, DocumentId, Index, DOCUMENTS_IDS_KEY};
+
+/// Key under which the set of words whose postings have been fully emptied by deletions,
+/// but that are still present in the words FST, is persisted. The FST is expensive to
+/// rebuild (it cannot cheaply drop individual words), so we track these lazily instead of
+/// rebuilding on every delete.
+const SOFT_DELETED_WORDS_KEY: &str = "soft-deleted-words";
+
+/// Once the number of soft-deleted words reaches this fraction of the FST's total word
+/// count, [`Index::delete_documents`] rebuilds the FST to drop them, so that autocomplete
+/// and fuzzy matching stop suggesting words with no remaining postings.
+const SOFT_DELETE_REBUILD_THRESHOLD: f64 = 0.10;
+
+impl Index {
+    /// Deletes `ids` from the index: their rows in `documents`, their presence in
+    /// `DOCUMENTS_IDS_KEY`, their entries in every word's posting lists (`word_positions`,
+    /// `word_position_docids`, `word_four_positions_docids` and `word_attribute_docids`),
+    /// and their entries in the facet postings (`facet_string_docids`,
+    /// `facet_number_docids`), removing now-empty keys as it goes. Words left with no
+    /// postings at all are recorded as soft-deleted and the words FST is rebuilt once
+    /// enough of them have accumulated (see [`SOFT_DELETE_REBUILD_THRESHOLD`]).
+    pub fn delete_documents(
+        &self,
+        wtxn: &mut heed::RwTxn,
+        ids: impl IntoIterator<Item = DocumentId>,
+    ) -> anyhow::Result<()>
+    {
+        let ids: RoaringBitmap = ids.into_iter().collect();
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        for id in ids.iter() {
+            self.documents.delete(wtxn, &BEU32::new(id))?;
+        }
+
+        let mut docids = self.main.get::<_, Str, RoaringBitmapCodec>(wtxn, DOCUMENTS_IDS_KEY)?
+            .unwrap_or_default();
+        docids -= &ids;
+        self.main.put::<_, Str, RoaringBitmapCodec>(wtxn, DOCUMENTS_IDS_KEY, &docids)?;
+
+        let position_emptied = remove_docids_from_postings(wtxn, self.word_position_docids, &ids)?;
+        let four_position_emptied = remove_docids_from_postings(wtxn, self.word_four_positions_docids, &ids)?;
+        let attribute_emptied = remove_docids_from_postings(wtxn, self.word_attribute_docids, &ids)?;
+        self.remove_documents_from_facets(wtxn, &ids)?;
+
+        // Every `(word, position)` key emptied out of `word_position_docids` means that
+        // position is no longer backed by any document: prune it from `word_positions` too,
+        // grouping by word so each word's bitmap is only read and rewritten once.
+        let mut dead_positions: BTreeMap<String, Vec<u32>> = BTreeMap::new();
+        for (word, position) in position_emptied {
+            dead_positions.entry(word).or_default().push(position);
+        }
+        for (word, positions) in &dead_positions {
+            if let Some(mut bitmap) = self.word_positions.get(wtxn, word.as_str())? {
+                for position in positions {
+                    bitmap.remove(*position);
+                }
+                if bitmap.is_empty() {
+                    self.word_positions.delete(wtxn, word.as_str())?;
+                } else {
+                    self.word_positions.put(wtxn, word.as_str(), &bitmap)?;
+                }
+            }
+        }
+
+        let mut emptied_words: BTreeSet<String> = dead_positions.into_keys().collect();
+        emptied_words.extend(four_position_emptied.into_iter().map(|(word, _)| word));
+        emptied_words.extend(attribute_emptied.into_iter().map(|(word, _)| word));
+
+        // A word is fully empty only once none of its positions have any postings left.
+        let mut soft_deleted = self.soft_deleted_words(wtxn)?;
+        for word in emptied_words {
+            let still_has_positions = self.word_position_docids.prefix_iter(wtxn, word.as_str())?
+                .next()
+                .is_some();
+            if !still_has_positions {
+                self.word_positions.delete(wtxn, word.as_str())?;
+                soft_deleted.insert(word);
+            }
+        }
+        self.put_soft_deleted_words(wtxn, &soft_deleted)?;
+
+        self.maybe_rebuild_fst(wtxn, &soft_deleted)?;
+
+        Ok(())
+    }
+
+    fn soft_deleted_words(&self, rtxn: &heed::RoTxn) -> anyhow::Result<BTreeSet<String>> {
+        match self.main.get::<_, Str, ByteSlice>(rtxn, SOFT_DELETED_WORDS_KEY)? {
+            Some(bytes) => Ok(serde_json::from_slice(bytes)?),
+            None => Ok(BTreeSet::new()),
+        }
+    }
+
+    fn put_soft_deleted_words(&self, wtxn: &mut heed::RwTxn, words: &BTreeSet<String>) -> anyhow::Result<()> {
+        let bytes = serde_json::to_vec(words)?;
+        Ok(self.main.put::<_, Str, ByteSlice>(wtxn, SOFT_DELETED_WORDS_KEY, &bytes)?)
+    }
+
+    /// Rebuilds the words FST without `soft_deleted` once they make up at least
+    /// [`SOFT_DELETE_REBUILD_THRESHOLD`] of it, so repeated delete/insert cycles don't leave
+    /// dangling terms in autocomplete and fuzzy matching forever.
+    fn maybe_rebuild_fst(&self, wtxn: &mut heed::RwTxn, soft_deleted: &BTreeSet<String>) -> anyhow::Result<()> {
+        if soft_deleted.is_empty() {
+            return Ok(());
+        }
+
+        let fst = match self.fst(wtxn)? {
+            Some(fst) => fst,
+            None => return Ok(()),
+        };
+
+        if (soft_deleted.len() as f64) < fst.len() as f64 * SOFT_DELETE_REBUILD_THRESHOLD {
+            return Ok(());
+        }
+
+        let mut builder = fst::SetBuilder::memory();
+        let mut stream = fst.stream();
+        while let Some(word) = stream.next() {
+            let word = std::str::from_utf8(word)?;
+            if !soft_deleted.contains(word) {
+                builder.insert(word)?;
+            }
+        }
+        let new_fst = builder.into_set();
+
+        drop(stream);
+        self.put_fst(wtxn, &new_fst)?;
+        self.put_soft_deleted_words(wtxn, &BTreeSet::new())?;
+
+        Ok(())
+    }
+}
+
+/// Removes `ids` from every `(word, position)` posting of `db`, deleting keys that become
+/// empty, and returns those now-empty `(word, position)` keys so the caller can prune them
+/// from any other database keyed the same way (e.g. `word_positions`).
+fn remove_docids_from_postings(
+    wtxn: &mut heed::RwTxn,
+    db: heed::Database<StrBEU32Codec, RoaringBitmapCodec>,
+    ids: &RoaringBitmap,
+) -> anyhow::Result<Vec<(String, u32)>>
+{
+    let mut to_update = Vec::new();
+    let mut to_delete = Vec::new();
+
+    for result in db.iter(wtxn)? {
+        let ((word, position), mut docids) = result?;
+        if docids.is_empty() || (&docids & ids).is_empty() {
+            continue;
+        }
+
+        docids -= ids;
+        if docids.is_empty() {
+            to_delete.push((word.to_owned(), position));
+        } else {
+            to_update.push((word.to_owned(), position, docids));
+        }
+    }
+
+    for (word, position, docids) in &to_update {
+        db.put(wtxn, &(word.as_str(), *position), docids)?;
+    }
+    for (word, position) in &to_delete {
+        db.delete(wtxn, &(word.as_str(), *position))?;
+    }
+
+    Ok(to_delete)
+}
@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Context};
+
+use crate::FieldId;
+
+/// The shape in which raw document bytes are stored in the `documents` database.
+/// Each variant knows how to turn its bytes, combined with a [`FieldsIdsMap`], into a
+/// field-id → value map, so the rest of the indexing and search code never has to care
+/// whether a given dataset originally came in as CSV, JSON or NDJSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentFormat {
+    /// One CSV line per document, columns positioned according to the `FieldsIdsMap`.
+    Csv,
+    /// A single JSON object per document.
+    Json,
+    /// A single JSON object per document, documents separated by newlines.
+    NdJson,
+}
+
+impl DocumentFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DocumentFormat::Csv => "csv",
+            DocumentFormat::Json => "json",
+            DocumentFormat::NdJson => "ndjson",
+        }
+    }
+
+    pub fn parse(s: &str) -> anyhow::Result<DocumentFormat> {
+        match s {
+            "csv" => Ok(DocumentFormat::Csv),
+            "json" => Ok(DocumentFormat::Json),
+            "ndjson" => Ok(DocumentFormat::NdJson),
+            otherwise => bail!("unknown document format {:?}", otherwise),
+        }
+    }
+
+    /// Parses a single raw document, as stored under one `documents` key, into a
+    /// field-id → value map using `fields_ids_map` to resolve CSV columns by position.
+    pub fn parse_document(
+        &self,
+        fields_ids_map: &FieldsIdsMap,
+        content: &[u8],
+    ) -> anyhow::Result<BTreeMap<FieldId, serde_json::Value>>
+    {
+        match self {
+            DocumentFormat::Csv => {
+                let mut rdr = csv::ReaderBuilder::new().has_headers(false).from_reader(content);
+                let record = rdr.records().next().context("empty CSV document")??;
+                let mut map = BTreeMap::new();
+                for (id, _name) in fields_ids_map.iter() {
+                    if let Some(value) = record.get(id as usize) {
+                        map.insert(id, serde_json::Value::String(value.to_owned()));
+                    }
+                }
+                Ok(map)
+            }
+            DocumentFormat::Json | DocumentFormat::NdJson => {
+                let value: serde_json::Value = serde_json::from_slice(content)?;
+                let object = match value {
+                    serde_json::Value::Object(object) => object,
+                    _ => bail!("expected a JSON object, found something else"),
+                };
+                let mut map = BTreeMap::new();
+                for (name, value) in object {
+                    if let Some(id) = fields_ids_map.id(&name) {
+                        map.insert(id, value);
+                    }
+                }
+                Ok(map)
+            }
+        }
+    }
+}
+
+/// A stable mapping between field names and the small integer ids used everywhere else
+/// in the index (postings, facet databases, …) so documents of heterogeneous shape can
+/// still share compact per-field storage.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct FieldsIdsMap {
+    names_ids: BTreeMap<String, FieldId>,
+    next_id: FieldId,
+}
+
+impl FieldsIdsMap {
+    pub fn new() -> FieldsIdsMap {
+        FieldsIdsMap::default()
+    }
+
+    /// Looks up the id of `name`, inserting it with a fresh id if it isn't known yet.
+    pub fn insert(&mut self, name: &str) -> Option<FieldId> {
+        if let Some(id) = self.names_ids.get(name) {
+            return Some(*id);
+        }
+        let id = self.next_id;
+        self.next_id = self.next_id.checked_add(1)?;
+        self.names_ids.insert(name.to_owned(), id);
+        Some(id)
+    }
+
+    pub fn id(&self, name: &str) -> Option<FieldId> {
+        self.names_ids.get(name).copied()
+    }
+
+    pub fn name(&self, id: FieldId) -> Option<&str> {
+        self.names_ids.iter().find(|(_, i)| **i == id).map(|(name, _)| name.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.names_ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names_ids.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (FieldId, &str)> {
+        self.names_ids.iter().map(|(name, id)| (*id, name.as_str()))
+    }
+}
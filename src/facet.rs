@@ -0,0 +1,473 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+
+use fst::{IntoStreamer, Streamer};
+use heed::{BytesDecode, BytesEncode};
+use roaring::RoaringBitmap;
+
+use crate::heed_codec::RoaringBitmapCodec;
+use crate::{DocumentId, FieldId, Index};
+
+/// A single facet value, either a string (exact-match, prefix/range over an FST) or a
+/// number (range queries over sortable big-endian keys).
+#[derive(Debug, Clone, PartialEq)]
+pub enum FacetValue {
+    String(String),
+    Number(f64),
+}
+
+impl FacetValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            FacetValue::String(s) => Some(s),
+            FacetValue::Number(_) => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            FacetValue::Number(n) => Some(*n),
+            FacetValue::String(_) => None,
+        }
+    }
+}
+
+/// The AST of a `filter` expression, e.g. `price < 50 AND category = "shoes"`.
+#[derive(Debug, Clone)]
+pub enum FilterCondition {
+    Equal(FieldId, FacetValue),
+    LowerThan(FieldId, f64),
+    GreaterThan(FieldId, f64),
+    Between(FieldId, f64, f64),
+    And(Box<FilterCondition>, Box<FilterCondition>),
+    Or(Box<FilterCondition>, Box<FilterCondition>),
+}
+
+impl FilterCondition {
+    /// Resolves this condition into the bitmap of documents it matches, by looking up the
+    /// relevant facet database(s) on `index`.
+    pub fn resolve(&self, rtxn: &heed::RoTxn, index: &Index) -> anyhow::Result<RoaringBitmap> {
+        match self {
+            FilterCondition::Equal(field_id, FacetValue::String(value)) => {
+                let key = (*field_id, value.as_str());
+                Ok(index.facet_string_docids.get(rtxn, &key)?.unwrap_or_default())
+            }
+            FilterCondition::Equal(field_id, FacetValue::Number(value)) => {
+                index.facet_number_range_docids(rtxn, *field_id, *value, *value)
+            }
+            FilterCondition::LowerThan(field_id, value) => {
+                let range = (Bound::Included((*field_id, f64::NEG_INFINITY)), Bound::Excluded((*field_id, *value)));
+                let mut docids = RoaringBitmap::new();
+                for result in index.facet_number_docids.range(rtxn, &range)? {
+                    docids |= result?.1;
+                }
+                Ok(docids)
+            }
+            FilterCondition::GreaterThan(field_id, value) => {
+                let range = (Bound::Excluded((*field_id, *value)), Bound::Included((*field_id, f64::INFINITY)));
+                let mut docids = RoaringBitmap::new();
+                for result in index.facet_number_docids.range(rtxn, &range)? {
+                    docids |= result?.1;
+                }
+                Ok(docids)
+            }
+            FilterCondition::Between(field_id, min, max) => {
+                index.facet_number_range_docids(rtxn, *field_id, *min, *max)
+            }
+            FilterCondition::And(lhs, rhs) => {
+                Ok(lhs.resolve(rtxn, index)? & rhs.resolve(rtxn, index)?)
+            }
+            FilterCondition::Or(lhs, rhs) => {
+                Ok(lhs.resolve(rtxn, index)? | rhs.resolve(rtxn, index)?)
+            }
+        }
+    }
+}
+
+/// Maps a facet value to the big-endian bytes used as its key suffix, ordering negative
+/// and positive numbers correctly by flipping the sign bit (and the rest for negatives).
+pub fn sortable_number_bytes(number: f64) -> [u8; 8] {
+    let bits = number.to_bits();
+    let sortable = if number.is_sign_negative() { !bits } else { bits | (1 << 63) };
+    sortable.to_be_bytes()
+}
+
+/// Codec for `(FieldId, &str)` facet keys: a big-endian field id followed by the raw
+/// string bytes, so keys naturally sort by field then by value.
+pub struct FacetStringCodec;
+
+impl<'a> BytesEncode<'a> for FacetStringCodec {
+    type EItem = (FieldId, &'a str);
+
+    fn bytes_encode(item: &'a Self::EItem) -> Option<Cow<'a, [u8]>> {
+        let (field_id, value) = item;
+        let mut bytes = Vec::with_capacity(2 + value.len());
+        bytes.extend_from_slice(&field_id.to_be_bytes());
+        bytes.extend_from_slice(value.as_bytes());
+        Some(Cow::Owned(bytes))
+    }
+}
+
+impl<'a> BytesDecode<'a> for FacetStringCodec {
+    type DItem = (FieldId, &'a str);
+
+    fn bytes_decode(bytes: &'a [u8]) -> Option<Self::DItem> {
+        if bytes.len() < 2 {
+            return None;
+        }
+        let (field_id_bytes, value_bytes) = bytes.split_at(2);
+        let field_id = FieldId::from_be_bytes(field_id_bytes.try_into().ok()?);
+        let value = std::str::from_utf8(value_bytes).ok()?;
+        Some((field_id, value))
+    }
+}
+
+/// Codec for `(FieldId, f64)` facet keys: a big-endian field id followed by the sortable
+/// big-endian encoding of the number, so a lexicographic range scan over keys of the same
+/// field id is equivalent to a numeric range query.
+pub struct FacetNumberCodec;
+
+impl<'a> BytesEncode<'a> for FacetNumberCodec {
+    type EItem = (FieldId, f64);
+
+    fn bytes_encode(item: &'a Self::EItem) -> Option<Cow<'a, [u8]>> {
+        let (field_id, number) = item;
+        let mut bytes = Vec::with_capacity(10);
+        bytes.extend_from_slice(&field_id.to_be_bytes());
+        bytes.extend_from_slice(&sortable_number_bytes(*number));
+        Some(Cow::Owned(bytes))
+    }
+}
+
+impl<'a> BytesDecode<'a> for FacetNumberCodec {
+    type DItem = (FieldId, f64);
+
+    fn bytes_decode(bytes: &'a [u8]) -> Option<Self::DItem> {
+        if bytes.len() != 10 {
+            return None;
+        }
+        let (field_id_bytes, number_bytes) = bytes.split_at(2);
+        let field_id = FieldId::from_be_bytes(field_id_bytes.try_into().ok()?);
+        let sortable = u64::from_be_bytes(number_bytes.try_into().ok()?);
+        let bits = if sortable & (1 << 63) != 0 { sortable & !(1 << 63) } else { !sortable };
+        Some((field_id, f64::from_bits(bits)))
+    }
+}
+
+impl Index {
+    /// Persists the distinct string values a facet field has taken, as an FST, so prefix
+    /// and lexicographic range queries over that field can be resolved without scanning the
+    /// whole `facet_string_docids` database.
+    pub fn put_facet_string_values_fst<A: AsRef<[u8]>>(
+        &self,
+        wtxn: &mut heed::RwTxn,
+        field_id: FieldId,
+        fst: &fst::Set<A>,
+    ) -> anyhow::Result<()>
+    {
+        let key = format!("facet-string-fst-{}", field_id);
+        Ok(self.main.put::<_, heed::types::Str, heed::types::ByteSlice>(wtxn, &key, fst.as_fst().as_bytes())?)
+    }
+
+    pub fn facet_string_values_fst<'t>(
+        &self,
+        rtxn: &'t heed::RoTxn,
+        field_id: FieldId,
+    ) -> anyhow::Result<Option<fst::Set<&'t [u8]>>>
+    {
+        let key = format!("facet-string-fst-{}", field_id);
+        match self.main.get::<_, heed::types::Str, heed::types::ByteSlice>(rtxn, &key)? {
+            Some(bytes) => Ok(Some(fst::Set::new(bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the union of the document ids whose value for `field_id` falls within
+    /// `[min, max]` (inclusive), by range-scanning the sortable `facet_number_docids` keys.
+    pub fn facet_number_range_docids(
+        &self,
+        rtxn: &heed::RoTxn,
+        field_id: FieldId,
+        min: f64,
+        max: f64,
+    ) -> anyhow::Result<RoaringBitmap>
+    {
+        let mut docids = RoaringBitmap::new();
+        for result in self.facet_number_docids.range(rtxn, &((field_id, min)..=(field_id, max)))? {
+            let (_key, bitmap) = result?;
+            docids |= bitmap;
+        }
+        Ok(docids)
+    }
+
+    /// Returns, for a string facet field, the number of candidate documents matching each
+    /// distinct value (restricted to `candidates`), to populate `SearchResult::facet_distribution`.
+    pub fn facet_string_distribution(
+        &self,
+        rtxn: &heed::RoTxn,
+        field_id: FieldId,
+        candidates: &RoaringBitmap,
+    ) -> anyhow::Result<BTreeMap<String, u64>>
+    {
+        let mut distribution = BTreeMap::new();
+
+        let fst = match self.facet_string_values_fst(rtxn, field_id)? {
+            Some(fst) => fst,
+            None => return Ok(distribution),
+        };
+
+        let mut stream = fst.into_stream();
+        while let Some(value) = stream.next() {
+            let value = std::str::from_utf8(value)?;
+            let key = (field_id, value);
+            if let Some(docids) = self.facet_string_docids.get(rtxn, &key)? {
+                let count = (&docids & candidates).len();
+                if count > 0 {
+                    distribution.insert(value.to_owned(), count);
+                }
+            }
+        }
+
+        Ok(distribution)
+    }
+
+    /// Records that `docid` takes `value` for the facet `field_id`, updating both the
+    /// posting bitmap and (for string values) the per-field values FST.
+    pub fn put_facet_value(
+        &self,
+        wtxn: &mut heed::RwTxn,
+        field_id: FieldId,
+        docid: DocumentId,
+        value: &FacetValue,
+    ) -> anyhow::Result<()>
+    {
+        match value {
+            FacetValue::String(s) => {
+                let key = (field_id, s.as_str());
+                let mut docids = self.facet_string_docids.get(wtxn, &key)?.unwrap_or_default();
+                docids.insert(docid);
+                self.facet_string_docids.put(wtxn, &key, &docids)?;
+
+                let mut values: Vec<String> = match self.facet_string_values_fst(wtxn, field_id)? {
+                    Some(fst) => {
+                        let mut stream = fst.into_stream();
+                        let mut values = Vec::new();
+                        while let Some(value) = stream.next() {
+                            values.push(std::str::from_utf8(value)?.to_owned());
+                        }
+                        values
+                    }
+                    None => Vec::new(),
+                };
+                if let Err(pos) = values.binary_search(s) {
+                    values.insert(pos, s.clone());
+                    let fst = fst::Set::from_iter(values.iter())?;
+                    self.put_facet_string_values_fst(wtxn, field_id, &fst)?;
+                }
+            }
+            FacetValue::Number(n) => {
+                let key = (field_id, *n);
+                let mut docids = self.facet_number_docids.get(wtxn, &key)?.unwrap_or_default();
+                docids.insert(docid);
+                self.facet_number_docids.put(wtxn, &key, &docids)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes `ids` from every facet posting (string and number), deleting now-empty keys
+    /// and pruning any string value left with no matching document from its field's FST.
+    /// Called by [`Index::delete_documents`](crate::Index::delete_documents).
+    pub(crate) fn remove_documents_from_facets(
+        &self,
+        wtxn: &mut heed::RwTxn,
+        ids: &RoaringBitmap,
+    ) -> anyhow::Result<()>
+    {
+        let mut to_update = Vec::new();
+        let mut emptied: Vec<(FieldId, String)> = Vec::new();
+
+        for result in self.facet_string_docids.iter(wtxn)? {
+            let ((field_id, value), mut docids) = result?;
+            if (&docids & ids).is_empty() {
+                continue;
+            }
+
+            docids -= ids;
+            if docids.is_empty() {
+                emptied.push((field_id, value.to_owned()));
+            } else {
+                to_update.push((field_id, value.to_owned(), docids));
+            }
+        }
+
+        for (field_id, value, docids) in to_update {
+            self.facet_string_docids.put(wtxn, &(field_id, value.as_str()), &docids)?;
+        }
+        for (field_id, value) in &emptied {
+            self.facet_string_docids.delete(wtxn, &(*field_id, value.as_str()))?;
+        }
+
+        let affected_fields: BTreeSet<FieldId> = emptied.iter().map(|(field_id, _)| *field_id).collect();
+        for field_id in affected_fields {
+            let emptied_values: BTreeSet<&str> = emptied.iter()
+                .filter(|(f, _)| *f == field_id)
+                .map(|(_, value)| value.as_str())
+                .collect();
+
+            if let Some(fst) = self.facet_string_values_fst(wtxn, field_id)? {
+                let mut builder = fst::SetBuilder::memory();
+                let mut stream = fst.stream();
+                while let Some(value) = stream.next() {
+                    let value = std::str::from_utf8(value)?;
+                    if !emptied_values.contains(value) {
+                        builder.insert(value)?;
+                    }
+                }
+                drop(stream);
+                self.put_facet_string_values_fst(wtxn, field_id, &builder.into_set())?;
+            }
+        }
+
+        let mut num_to_update = Vec::new();
+        let mut num_to_delete = Vec::new();
+
+        for result in self.facet_number_docids.iter(wtxn)? {
+            let ((field_id, value), mut docids) = result?;
+            if (&docids & ids).is_empty() {
+                continue;
+            }
+
+            docids -= ids;
+            if docids.is_empty() {
+                num_to_delete.push((field_id, value));
+            } else {
+                num_to_update.push((field_id, value, docids));
+            }
+        }
+
+        for (field_id, value, docids) in num_to_update {
+            self.facet_number_docids.put(wtxn, &(field_id, value), &docids)?;
+        }
+        for (field_id, value) in num_to_delete {
+            self.facet_number_docids.delete(wtxn, &(field_id, value))?;
+        }
+
+        Ok(())
+    }
+}
+
+pub(crate) type FacetStringDatabase = heed::Database<FacetStringCodec, RoaringBitmapCodec>;
+pub(crate) type FacetNumberDatabase = heed::Database<FacetNumberCodec, RoaringBitmapCodec>;
+
+#[cfg(test)]
+mod tests {
+    use heed::EnvOpenOptions;
+
+    use super::*;
+
+    #[test]
+    fn sortable_number_bytes_orders_negatives_zero_and_positives() {
+        let neg = sortable_number_bytes(-5.0);
+        let zero = sortable_number_bytes(0.0);
+        let pos = sortable_number_bytes(5.0);
+        assert!(neg < zero);
+        assert!(zero < pos);
+    }
+
+    #[test]
+    fn sortable_number_bytes_orders_infinities_at_the_extremes() {
+        let neg_inf = sortable_number_bytes(f64::NEG_INFINITY);
+        let mid = sortable_number_bytes(0.0);
+        let pos_inf = sortable_number_bytes(f64::INFINITY);
+        assert!(neg_inf < mid);
+        assert!(mid < pos_inf);
+    }
+
+    #[test]
+    fn facet_number_codec_round_trips_negatives_zero_and_infinities() {
+        for value in [-42.5, 0.0, 42.5, f64::NEG_INFINITY, f64::INFINITY] {
+            let encoded = FacetNumberCodec::bytes_encode(&(3, value)).unwrap();
+            let (field_id, decoded) = FacetNumberCodec::bytes_decode(&encoded).unwrap();
+            assert_eq!(field_id, 3);
+            assert_eq!(decoded, value);
+        }
+    }
+
+    #[test]
+    fn facet_number_codec_rejects_truncated_input() {
+        assert!(FacetNumberCodec::bytes_decode(&[0, 3, 1, 2]).is_none());
+    }
+
+    #[test]
+    fn facet_string_codec_round_trips() {
+        let encoded = FacetStringCodec::bytes_encode(&(7, "shoes")).unwrap();
+        let (field_id, value) = FacetStringCodec::bytes_decode(&encoded).unwrap();
+        assert_eq!(field_id, 7);
+        assert_eq!(value, "shoes");
+    }
+
+    #[test]
+    fn facet_string_codec_rejects_input_shorter_than_the_field_id_prefix() {
+        assert!(FacetStringCodec::bytes_decode(&[0]).is_none());
+    }
+
+    fn temp_index() -> (tempfile::TempDir, heed::Env, Index) {
+        let dir = tempfile::tempdir().unwrap();
+        let env = EnvOpenOptions::new()
+            .map_size(10 * 1024 * 1024)
+            .max_dbs(10)
+            .open(&dir)
+            .unwrap();
+        let index = Index::new(&env).unwrap();
+        (dir, env, index)
+    }
+
+    #[test]
+    fn facet_number_range_docids_only_returns_values_within_range() {
+        let (_dir, env, index) = temp_index();
+        let mut wtxn = env.write_txn().unwrap();
+        index.put_facet_value(&mut wtxn, 0, 1, &FacetValue::Number(10.0)).unwrap();
+        index.put_facet_value(&mut wtxn, 0, 2, &FacetValue::Number(20.0)).unwrap();
+        index.put_facet_value(&mut wtxn, 0, 3, &FacetValue::Number(30.0)).unwrap();
+        wtxn.commit().unwrap();
+
+        let rtxn = env.read_txn().unwrap();
+        let docids = index.facet_number_range_docids(&rtxn, 0, 15.0, 25.0).unwrap();
+        assert_eq!(docids.iter().collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[test]
+    fn remove_documents_from_facets_prunes_empty_keys_and_fst_entries() {
+        let (_dir, env, index) = temp_index();
+        let mut wtxn = env.write_txn().unwrap();
+        index.put_facet_value(&mut wtxn, 0, 1, &FacetValue::String("red".into())).unwrap();
+        index.put_facet_value(&mut wtxn, 0, 2, &FacetValue::String("red".into())).unwrap();
+        index.put_facet_value(&mut wtxn, 0, 3, &FacetValue::String("blue".into())).unwrap();
+        index.put_facet_value(&mut wtxn, 1, 1, &FacetValue::Number(10.0)).unwrap();
+        wtxn.commit().unwrap();
+
+        let mut wtxn = env.write_txn().unwrap();
+        let mut ids = RoaringBitmap::new();
+        ids.insert(1);
+        ids.insert(3);
+        index.remove_documents_from_facets(&mut wtxn, &ids).unwrap();
+        wtxn.commit().unwrap();
+
+        let rtxn = env.read_txn().unwrap();
+        // "blue" had only document 3, now fully removed.
+        assert!(index.facet_string_docids.get(&rtxn, &(0, "blue")).unwrap().is_none());
+        // "red" keeps document 2.
+        let red = index.facet_string_docids.get(&rtxn, &(0, "red")).unwrap().unwrap();
+        assert_eq!(red.iter().collect::<Vec<_>>(), vec![2]);
+        // The FST no longer advertises "blue" as a value, but still has "red".
+        let fst = index.facet_string_values_fst(&rtxn, 0).unwrap().unwrap();
+        assert!(!fst.contains("blue"));
+        assert!(fst.contains("red"));
+        // Document 1's number facet posting is gone entirely.
+        let numbers = index.facet_number_range_docids(&rtxn, 1, f64::NEG_INFINITY, f64::INFINITY).unwrap();
+        assert!(numbers.is_empty());
+    }
+}
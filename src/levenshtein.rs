@@ -0,0 +1,237 @@
+use fst::Automaton;
+
+/// Returns the maximum edit distance admitted for a word of the given length,
+/// following the same thresholds used by most typo-tolerant search engines:
+/// no typo below 4 characters, one from 4 to 7 characters, two from 8 characters on.
+pub fn max_typos(word_len: usize) -> u32 {
+    if word_len >= 8 {
+        2
+    } else if word_len >= 4 {
+        1
+    } else {
+        0
+    }
+}
+
+/// A classic Levenshtein-automaton state: the set of reachable `(input_index, errors)`
+/// positions, kept sorted and deduplicated so states can be compared cheaply.
+#[derive(Clone, PartialEq, Eq)]
+pub struct LevenshteinState {
+    positions: Vec<(usize, u32)>,
+}
+
+/// A Levenshtein DFA over a fixed query word and a maximum edit distance, intended to be
+/// intersected with a [`fst::Set`] via the [`Automaton`] trait to enumerate every word of
+/// the dictionary within `max_distance` edits of `query`.
+#[derive(Clone)]
+pub struct LevenshteinDfa {
+    query: Vec<u8>,
+    max_distance: u32,
+}
+
+impl LevenshteinDfa {
+    pub fn new(query: &str, max_distance: u32) -> LevenshteinDfa {
+        LevenshteinDfa { query: query.as_bytes().to_vec(), max_distance }
+    }
+
+    /// Builds a DFA for `query` using the distance threshold dictated by its length.
+    pub fn for_word(query: &str) -> LevenshteinDfa {
+        LevenshteinDfa::new(query, max_typos(query.chars().count()))
+    }
+
+    fn start_state(&self) -> LevenshteinState {
+        let mut positions = vec![(0, 0)];
+        close_deletions(&self.query, self.max_distance, &mut positions);
+        positions.sort_unstable();
+        positions.dedup();
+        LevenshteinState { positions }
+    }
+
+    fn step(&self, state: &LevenshteinState, byte: u8) -> LevenshteinState {
+        // Deletion is a zero-width transition (it advances the query without consuming an
+        // input byte), so it must be folded into the source positions *before* matching
+        // this byte against them — otherwise a deletion occurring in the middle of the
+        // query is never reachable, only leading/trailing ones.
+        let mut sources = state.positions.clone();
+        close_deletions(&self.query, self.max_distance, &mut sources);
+
+        let mut positions = Vec::new();
+
+        for &(idx, errors) in &sources {
+            if errors > self.max_distance {
+                continue;
+            }
+
+            // Match: advance without spending an edit.
+            if idx < self.query.len() && self.query[idx] == byte {
+                positions.push((idx + 1, errors));
+            }
+
+            if errors < self.max_distance {
+                // Substitution.
+                if idx < self.query.len() {
+                    positions.push((idx + 1, errors + 1));
+                }
+                // Insertion (consume the input byte without advancing the query).
+                positions.push((idx, errors + 1));
+            }
+        }
+
+        close_deletions(&self.query, self.max_distance, &mut positions);
+        positions.sort_unstable();
+        positions.dedup();
+        prune_subsumed(&mut positions);
+        LevenshteinState { positions }
+    }
+
+    fn is_match(&self, state: &LevenshteinState) -> bool {
+        state.positions.iter().any(|&(idx, errors)| {
+            errors + (self.query.len() - idx) as u32 <= self.max_distance
+        })
+    }
+
+    /// The smallest number of edits among the accepting positions of `state`, used to weight
+    /// matches by how close they are to the original query when ranking results.
+    pub fn distance(&self, state: &LevenshteinState) -> Option<u32> {
+        state.positions.iter()
+            .filter(|&&(idx, errors)| errors + (self.query.len() - idx) as u32 <= self.max_distance)
+            .map(|&(idx, errors)| errors + (self.query.len() - idx) as u32)
+            .min()
+    }
+}
+
+/// Extends `positions` with every position reachable by one or more deletions (epsilon
+/// transitions that advance the query index without consuming an input byte), so that a
+/// position subsumed and dropped by [`prune_subsumed`] at the end of one step can still be
+/// reached again as a deletion source at the start of the next one.
+fn close_deletions(query: &[u8], max_distance: u32, positions: &mut Vec<(usize, u32)>) {
+    let mut i = 0;
+    while i < positions.len() {
+        let (idx, errors) = positions[i];
+        if errors < max_distance && idx < query.len() {
+            let next = (idx + 1, errors + 1);
+            if !positions.contains(&next) {
+                positions.push(next);
+            }
+        }
+        i += 1;
+    }
+}
+
+/// Drops positions that are dominated by another position reached with fewer errors and
+/// lying, in either direction, no more query positions away than the difference in errors,
+/// keeping the state vector small.
+fn prune_subsumed(positions: &mut Vec<(usize, u32)>) {
+    positions.retain(|&(idx, errors)| {
+        !positions.iter().any(|&(other_idx, other_errors)| {
+            (other_idx, other_errors) != (idx, errors)
+                && other_errors <= errors
+                && (other_idx as i64 - idx as i64).unsigned_abs() as u32 <= errors - other_errors
+        })
+    });
+}
+
+impl Automaton for LevenshteinDfa {
+    type State = Option<LevenshteinState>;
+
+    fn start(&self) -> Self::State {
+        Some(self.start_state())
+    }
+
+    fn is_match(&self, state: &Self::State) -> bool {
+        state.as_ref().map_or(false, |s| self.is_match(s))
+    }
+
+    fn can_match(&self, state: &Self::State) -> bool {
+        state.is_some()
+    }
+
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State {
+        state.as_ref().map(|s| self.step(s, byte)).filter(|s| !s.positions.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Streams `word` through `dfa` and returns the edit distance of the final state, or
+    /// `None` if the automaton dies (too many errors) or never reaches an accepting state.
+    fn edit_distance(dfa: &LevenshteinDfa, word: &str) -> Option<u32> {
+        let mut state = Automaton::start(dfa);
+        for &byte in word.as_bytes() {
+            if !Automaton::can_match(dfa, &state) {
+                return None;
+            }
+            state = Automaton::accept(dfa, &state, byte);
+        }
+        state.and_then(|s| dfa.distance(&s))
+    }
+
+    #[test]
+    fn max_typos_thresholds() {
+        assert_eq!(max_typos(0), 0);
+        assert_eq!(max_typos(3), 0);
+        assert_eq!(max_typos(4), 1);
+        assert_eq!(max_typos(7), 1);
+        assert_eq!(max_typos(8), 2);
+        assert_eq!(max_typos(20), 2);
+    }
+
+    #[test]
+    fn exact_match_has_zero_distance() {
+        let dfa = LevenshteinDfa::new("hello", 2);
+        assert_eq!(edit_distance(&dfa, "hello"), Some(0));
+    }
+
+    #[test]
+    fn substitution_is_found() {
+        let dfa = LevenshteinDfa::new("abc", 1);
+        assert_eq!(edit_distance(&dfa, "abd"), Some(1));
+    }
+
+    #[test]
+    fn insertion_is_found() {
+        let dfa = LevenshteinDfa::new("abc", 1);
+        assert_eq!(edit_distance(&dfa, "abxc"), Some(1));
+    }
+
+    #[test]
+    fn interior_deletion_is_found() {
+        // Regression test for the bug fixed in 2cd1ab2: "ac" is "abc" with the interior
+        // "b" deleted, an edit distance of 1, but the automaton used to only ever find
+        // leading/trailing deletions.
+        let dfa = LevenshteinDfa::new("abc", 1);
+        assert_eq!(edit_distance(&dfa, "ac"), Some(1));
+    }
+
+    #[test]
+    fn multiple_interior_deletions_are_found() {
+        let dfa = LevenshteinDfa::new("abcde", 2);
+        assert_eq!(edit_distance(&dfa, "ace"), Some(2));
+    }
+
+    #[test]
+    fn leading_and_trailing_deletions_are_found() {
+        let dfa = LevenshteinDfa::new("abc", 1);
+        assert_eq!(edit_distance(&dfa, "bc"), Some(1));
+        assert_eq!(edit_distance(&dfa, "ab"), Some(1));
+    }
+
+    #[test]
+    fn distance_beyond_max_is_rejected() {
+        let dfa = LevenshteinDfa::new("abc", 1);
+        assert_eq!(edit_distance(&dfa, "xyz"), None);
+    }
+
+    #[test]
+    fn for_word_picks_the_threshold_for_its_length() {
+        // 3 chars: max_typos == 0, so even a single substitution must be rejected.
+        let short = LevenshteinDfa::for_word("cat");
+        assert_eq!(edit_distance(&short, "cot"), None);
+
+        // 8 chars: max_typos == 2, enough to admit a doubled-letter typo like "exampple".
+        let long = LevenshteinDfa::for_word("exampple");
+        assert_eq!(edit_distance(&long, "example"), Some(1));
+    }
+}
@@ -1,5 +1,10 @@
 mod criterion;
+mod delete;
+mod document_format;
+mod facet;
+mod levenshtein;
 mod node;
+mod phrase;
 mod query_tokens;
 mod search;
 pub mod heed_codec;
@@ -9,13 +14,20 @@ use std::collections::HashMap;
 use std::hash::BuildHasherDefault;
 
 use anyhow::Context;
+use fst::{IntoStreamer, Streamer};
 use fxhash::{FxHasher32, FxHasher64};
 use heed::types::*;
 use heed::{PolyDatabase, Database};
+use roaring::RoaringBitmap;
 
 pub use self::search::{Search, SearchResult};
 pub use self::criterion::{Criterion, default_criteria};
+pub use self::document_format::{DocumentFormat, FieldsIdsMap};
+pub use self::facet::{FacetValue, FilterCondition};
+pub use self::phrase::Phrase;
+use self::facet::{FacetNumberDatabase, FacetStringDatabase};
 use self::heed_codec::{RoaringBitmapCodec, StrBEU32Codec};
+use self::levenshtein::LevenshteinDfa;
 
 pub type FastMap4<K, V> = HashMap<K, V, BuildHasherDefault<FxHasher32>>;
 pub type FastMap8<K, V> = HashMap<K, V, BuildHasherDefault<FxHasher64>>;
@@ -24,16 +36,18 @@ pub type SmallVec32<T> = smallvec::SmallVec<[T; 32]>;
 pub type SmallVec16<T> = smallvec::SmallVec<[T; 16]>;
 pub type BEU32 = heed::zerocopy::U32<heed::byteorder::BE>;
 pub type DocumentId = u32;
+pub type FieldId = u16;
 pub type Attribute = u32;
 pub type Position = u32;
 
 const WORDS_FST_KEY: &str = "words-fst";
-const HEADERS_KEY: &str = "headers";
-const DOCUMENTS_IDS_KEY: &str = "documents-ids";
+const FIELDS_IDS_MAP_KEY: &str = "fields-ids-map";
+const DOCUMENT_FORMAT_KEY: &str = "document-format";
+pub(crate) const DOCUMENTS_IDS_KEY: &str = "documents-ids";
 
 #[derive(Clone)]
 pub struct Index {
-    /// Contains many different types (e.g. the documents CSV headers).
+    /// Contains many different types (e.g. the fields ids map, the document format).
     pub main: PolyDatabase,
     /// A word and all the positions where it appears in the whole dataset.
     pub word_positions: Database<Str, RoaringBitmapCodec>,
@@ -43,8 +57,14 @@ pub struct Index {
     pub word_four_positions_docids: Database<StrBEU32Codec, RoaringBitmapCodec>,
     /// Maps a word and an attribute (u32) to all the documents ids where the given word appears.
     pub word_attribute_docids: Database<StrBEU32Codec, RoaringBitmapCodec>,
-    /// Maps the document id to the document as a CSV line.
+    /// Maps the document id to its raw content, encoded according to the index's
+    /// [`DocumentFormat`] (a CSV line, or a JSON object).
     pub documents: Database<OwnedType<BEU32>, ByteSlice>,
+    /// Maps a facet field id and an exact string value to the documents ids that have it.
+    pub facet_string_docids: FacetStringDatabase,
+    /// Maps a facet field id and a number, stored as sortable big-endian bytes, to the
+    /// documents ids that have it, so range queries become bitmap unions over a key range.
+    pub facet_number_docids: FacetNumberDatabase,
 }
 
 impl Index {
@@ -56,28 +76,43 @@ impl Index {
             word_four_positions_docids: env.create_database(Some("word-four-positions-docids"))?,
             word_attribute_docids: env.create_database(Some("word-attribute-docids"))?,
             documents: env.create_database(Some("documents"))?,
+            facet_string_docids: env.create_database(Some("facet-string-docids"))?,
+            facet_number_docids: env.create_database(Some("facet-number-docids"))?,
         })
     }
 
-    pub fn put_headers(&self, wtxn: &mut heed::RwTxn, headers: &[u8]) -> anyhow::Result<()> {
-        Ok(self.main.put::<_, Str, ByteSlice>(wtxn, HEADERS_KEY, headers)?)
+    /// Persists the mapping between field names and their stable ids, used to interpret
+    /// every document regardless of which [`DocumentFormat`] it was ingested with.
+    pub fn put_fields_ids_map(&self, wtxn: &mut heed::RwTxn, map: &FieldsIdsMap) -> anyhow::Result<()> {
+        let bytes = serde_json::to_vec(map)?;
+        Ok(self.main.put::<_, Str, ByteSlice>(wtxn, FIELDS_IDS_MAP_KEY, &bytes)?)
     }
 
-    pub fn headers<'t>(&self, rtxn: &'t heed::RoTxn) -> heed::Result<Option<&'t [u8]>> {
-        self.main.get::<_, Str, ByteSlice>(rtxn, HEADERS_KEY)
+    pub fn fields_ids_map<'t>(&self, rtxn: &'t heed::RoTxn) -> anyhow::Result<Option<FieldsIdsMap>> {
+        match self.main.get::<_, Str, ByteSlice>(rtxn, FIELDS_IDS_MAP_KEY)? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(bytes)?)),
+            None => Ok(None),
+        }
     }
 
-    pub fn number_of_attributes<'t>(&self, rtxn: &'t heed::RoTxn) -> anyhow::Result<Option<usize>> {
-        match self.headers(rtxn)? {
-            Some(headers) => {
-                let mut rdr = csv::Reader::from_reader(headers);
-                let headers = rdr.headers()?;
-                Ok(Some(headers.len()))
-            }
+    /// Persists the format (CSV, JSON or NDJSON) that every document in `documents` was
+    /// encoded with at ingestion time.
+    pub fn put_document_format(&self, wtxn: &mut heed::RwTxn, format: DocumentFormat) -> anyhow::Result<()> {
+        Ok(self.main.put::<_, Str, Str>(wtxn, DOCUMENT_FORMAT_KEY, format.as_str())?)
+    }
+
+    pub fn document_format<'t>(&self, rtxn: &'t heed::RoTxn) -> anyhow::Result<Option<DocumentFormat>> {
+        match self.main.get::<_, Str, Str>(rtxn, DOCUMENT_FORMAT_KEY)? {
+            Some(format) => Ok(Some(DocumentFormat::parse(format)?)),
             None => Ok(None),
         }
     }
 
+    /// Returns the number of distinct fields known across every ingested document.
+    pub fn number_of_attributes<'t>(&self, rtxn: &'t heed::RoTxn) -> anyhow::Result<Option<usize>> {
+        Ok(self.fields_ids_map(rtxn)?.map(|map| map.len()))
+    }
+
     pub fn put_fst<A: AsRef<[u8]>>(&self, wtxn: &mut heed::RwTxn, fst: &fst::Set<A>) -> anyhow::Result<()> {
         Ok(self.main.put::<_, Str, ByteSlice>(wtxn, WORDS_FST_KEY, fst.as_fst().as_bytes())?)
     }
@@ -89,11 +124,32 @@ impl Index {
         }
     }
 
-    /// Returns a [`Vec`] of the requested documents. Returns an error if a document is missing.
+    /// Returns a [`Vec`] of the requested documents, each as a field-id → value map decoded
+    /// according to the index's [`DocumentFormat`]. Returns an error if a document is missing
+    /// or if no document format/fields ids map has been configured yet.
     pub fn documents<'t>(
         &self,
         rtxn: &'t heed::RoTxn,
         iter: impl IntoIterator<Item=DocumentId>,
+    ) -> anyhow::Result<Vec<(DocumentId, std::collections::BTreeMap<FieldId, serde_json::Value>)>>
+    {
+        let format = self.document_format(rtxn)?.context("Could not find the document format")?;
+        let fields_ids_map = self.fields_ids_map(rtxn)?.context("Could not find the fields ids map")?;
+
+        iter.into_iter().map(|id| {
+            let content = self.documents.get(rtxn, &BEU32::new(id))?
+                .with_context(|| format!("Could not find document {}", id))?;
+            let document = format.parse_document(&fields_ids_map, content)?;
+            Ok((id, document))
+        }).collect()
+    }
+
+    /// Returns the raw, undecoded bytes of the requested documents, as they were stored at
+    /// ingestion time. Most callers should prefer [`Index::documents`].
+    pub fn raw_documents<'t>(
+        &self,
+        rtxn: &'t heed::RoTxn,
+        iter: impl IntoIterator<Item=DocumentId>,
     ) -> anyhow::Result<Vec<(DocumentId, Vec<u8>)>>
     {
         iter.into_iter().map(|id| {
@@ -113,4 +169,49 @@ impl Index {
     pub fn search<'a>(&'a self, rtxn: &'a heed::RoTxn) -> Search<'a> {
         Search::new(rtxn, self)
     }
+
+    /// Returns the union, across every attribute, of the `word_attribute_docids` postings
+    /// for `word`, i.e. every document id containing `word` anywhere.
+    pub fn word_documents(&self, rtxn: &heed::RoTxn, word: &str) -> anyhow::Result<RoaringBitmap> {
+        let mut docids = RoaringBitmap::new();
+        for result in self.word_attribute_docids.prefix_iter(rtxn, word)? {
+            let (_key, bitmap) = result?;
+            docids |= bitmap;
+        }
+        Ok(docids)
+    }
+
+    /// Finds every word of the words FST within the typo-tolerance distance of `word`
+    /// (see [`levenshtein::max_typos`]) and returns, for each of them, the union of its
+    /// `word_attribute_docids` postings alongside the edit distance that separates it from
+    /// `word`, so callers can union the postings of every candidate and weight results by
+    /// closeness.
+    pub fn fuzzy_matching_words<'t>(
+        &self,
+        rtxn: &'t heed::RoTxn,
+        word: &str,
+    ) -> anyhow::Result<Vec<(String, u32, RoaringBitmap)>>
+    {
+        let fst = match self.fst(rtxn)? {
+            Some(fst) => fst,
+            None => return Ok(Vec::new()),
+        };
+
+        let dfa = LevenshteinDfa::for_word(word);
+        let mut stream = fst.search_with_state(&dfa).into_stream();
+        let mut matches = Vec::new();
+
+        while let Some((word_bytes, state)) = stream.next() {
+            let distance = match dfa.distance(state.as_ref().expect("matched state")) {
+                Some(distance) => distance,
+                None => continue,
+            };
+            let matched_word = std::str::from_utf8(word_bytes)?.to_owned();
+            let docids = self.word_documents(rtxn, &matched_word)?;
+
+            matches.push((matched_word, distance, docids));
+        }
+
+        Ok(matches)
+    }
 }
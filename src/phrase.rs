@@ -0,0 +1,262 @@
+use roaring::RoaringBitmap;
+
+use crate::{DocumentId, FastMap4, Index};
+
+/// A quoted phrase extracted from a query, e.g. `"new york"` becomes
+/// `Phrase { words: vec!["new".into(), "york".into()] }`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Phrase {
+    pub words: Vec<String>,
+}
+
+impl Phrase {
+    pub fn new(words: Vec<String>) -> Phrase {
+        Phrase { words }
+    }
+}
+
+impl Index {
+    /// Coarse pre-filter: documents that contain every word of `words` at all, computed
+    /// from `word_four_positions_docids` so the expensive exact-position check below only
+    /// has to run over documents that stand a chance of matching.
+    fn coarse_phrase_candidates(&self, rtxn: &heed::RoTxn, words: &[String]) -> anyhow::Result<RoaringBitmap> {
+        let mut candidates: Option<RoaringBitmap> = None;
+
+        for word in words {
+            let mut docids = RoaringBitmap::new();
+            for result in self.word_four_positions_docids.prefix_iter(rtxn, word.as_str())? {
+                let (_key, bitmap) = result?;
+                docids |= bitmap;
+            }
+
+            candidates = Some(match candidates {
+                Some(candidates) => candidates & docids,
+                None => docids,
+            });
+        }
+
+        Ok(candidates.unwrap_or_default())
+    }
+
+    /// Returns the documents where `phrase.words` appear strictly adjacent and in order,
+    /// e.g. for `"new york"`, only documents with "new" immediately followed by "york".
+    /// This is the hard filter used when a query contains a quoted phrase.
+    pub fn phrase_documents(&self, rtxn: &heed::RoTxn, phrase: &Phrase) -> anyhow::Result<RoaringBitmap> {
+        let word = match phrase.words.as_slice() {
+            // A single-word "phrase" has no adjacency to check: fall back to a normal
+            // word match instead of the `proximity_documents` empty-`rest` early return.
+            [word] => Some(word),
+            _ => None,
+        };
+        if let Some(word) = word {
+            return self.word_documents(rtxn, word);
+        }
+
+        // Strict adjacency means the smallest window that can possibly fit every word is
+        // also the only one allowed: the span from the first to the last word is exactly
+        // `words.len() - 1` positions.
+        let window = phrase.words.len() as u32 - 1;
+        self.proximity_documents(rtxn, &phrase.words, window)
+            .map(|matches| matches.into_iter().map(|(docid, _distance)| docid).collect())
+    }
+
+    /// Returns, for every candidate document, the smallest window (in positions) within
+    /// which all of `words` occur in order, provided it does not exceed `max_window`. Used
+    /// both as the exact-phrase filter (`max_window == words.len() - 1`) and as a ranking signal
+    /// feeding the [`Criterion`](crate::Criterion) chain when a looser proximity match is allowed.
+    /// Every following word is checked on its own against the first word's position, so when
+    /// `max_window` leaves slack and there are three or more words, the order of the words
+    /// after the first one relative to each other is not checked.
+    pub fn proximity_documents(
+        &self,
+        rtxn: &heed::RoTxn,
+        words: &[String],
+        max_window: u32,
+    ) -> anyhow::Result<FastMap4<DocumentId, u32>>
+    {
+        let mut matches = FastMap4::default();
+
+        let (first, rest) = match words.split_first() {
+            Some(split) => split,
+            None => return Ok(matches),
+        };
+        if rest.is_empty() {
+            return Ok(matches);
+        }
+
+        let coarse_candidates = self.coarse_phrase_candidates(rtxn, words)?;
+        if coarse_candidates.is_empty() {
+            return Ok(matches);
+        }
+
+        let first_positions = match self.word_positions.get(rtxn, first)? {
+            Some(positions) => positions,
+            None => return Ok(matches),
+        };
+
+        for start in first_positions.iter() {
+            let start_docids = match self.word_position_docids.get(rtxn, &(first.as_str(), start))? {
+                Some(docids) => docids & &coarse_candidates,
+                None => continue,
+            };
+            if start_docids.is_empty() {
+                continue;
+            }
+
+            // Try every window width, smallest first, so the first hit for a document is
+            // also its best (smallest) proximity score.
+            'windows: for window in (rest.len() as u32)..=max_window {
+                let mut remaining = start_docids.clone();
+
+                for (i, word) in rest.iter().enumerate() {
+                    // The i-th following word may sit at any offset from the first word that
+                    // leaves room for the words after it inside `window`.
+                    let min_offset = (i + 1) as u32;
+                    let max_offset = window - (rest.len() as u32 - 1 - i as u32);
+
+                    let mut docids_at_any_offset = RoaringBitmap::new();
+                    for offset in min_offset..=max_offset {
+                        if let Some(docids) = self.word_position_docids.get(rtxn, &(word.as_str(), start + offset))? {
+                            docids_at_any_offset |= docids;
+                        }
+                    }
+
+                    remaining &= docids_at_any_offset;
+                    if remaining.is_empty() {
+                        continue 'windows;
+                    }
+                }
+
+                for docid in remaining {
+                    matches.entry(docid)
+                        .and_modify(|best| *best = (*best).min(window))
+                        .or_insert(window);
+                }
+                break;
+            }
+        }
+
+        Ok(matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use heed::EnvOpenOptions;
+
+    use super::*;
+
+    fn temp_index() -> (tempfile::TempDir, heed::Env, Index) {
+        let dir = tempfile::tempdir().unwrap();
+        let env = EnvOpenOptions::new()
+            .map_size(10 * 1024 * 1024)
+            .max_dbs(10)
+            .open(&dir)
+            .unwrap();
+        let index = Index::new(&env).unwrap();
+        (dir, env, index)
+    }
+
+    /// Records `word` occurring at `position` (attribute 0) in `docid`, merging with
+    /// whatever is already indexed for that word/position/attribute.
+    fn index_word(index: &Index, wtxn: &mut heed::RwTxn, word: &str, position: u32, docid: DocumentId) {
+        let mut docids = RoaringBitmap::new();
+        docids.insert(docid);
+
+        let mut positions = index.word_positions.get(wtxn, word).unwrap().unwrap_or_default();
+        positions.insert(position);
+        index.word_positions.put(wtxn, word, &positions).unwrap();
+
+        let mut at_position = index.word_position_docids.get(wtxn, &(word, position)).unwrap().unwrap_or_default();
+        at_position |= &docids;
+        index.word_position_docids.put(wtxn, &(word, position), &at_position).unwrap();
+
+        let group = (position / 4) * 4;
+        let mut at_group = index.word_four_positions_docids.get(wtxn, &(word, group)).unwrap().unwrap_or_default();
+        at_group |= &docids;
+        index.word_four_positions_docids.put(wtxn, &(word, group), &at_group).unwrap();
+
+        let mut at_attribute = index.word_attribute_docids.get(wtxn, &(word, 0)).unwrap().unwrap_or_default();
+        at_attribute |= &docids;
+        index.word_attribute_docids.put(wtxn, &(word, 0), &at_attribute).unwrap();
+    }
+
+    #[test]
+    fn single_word_phrase_falls_back_to_a_word_match() {
+        let (_dir, env, index) = temp_index();
+        let mut wtxn = env.write_txn().unwrap();
+        index_word(&index, &mut wtxn, "hello", 0, 1);
+        wtxn.commit().unwrap();
+
+        let rtxn = env.read_txn().unwrap();
+        let phrase = Phrase::new(vec!["hello".into()]);
+        let docids = index.phrase_documents(&rtxn, &phrase).unwrap();
+        assert_eq!(docids.iter().collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn two_word_phrase_requires_strict_adjacency() {
+        let (_dir, env, index) = temp_index();
+        let mut wtxn = env.write_txn().unwrap();
+        // Document 1: "new" immediately followed by "york".
+        index_word(&index, &mut wtxn, "new", 0, 1);
+        index_word(&index, &mut wtxn, "york", 1, 1);
+        // Document 2: the same two words, but not adjacent.
+        index_word(&index, &mut wtxn, "new", 0, 2);
+        index_word(&index, &mut wtxn, "york", 2, 2);
+        wtxn.commit().unwrap();
+
+        let rtxn = env.read_txn().unwrap();
+        let phrase = Phrase::new(vec!["new".into(), "york".into()]);
+        let docids = index.phrase_documents(&rtxn, &phrase).unwrap();
+        assert_eq!(docids.iter().collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn three_word_phrase_matches_the_full_span() {
+        let (_dir, env, index) = temp_index();
+        let mut wtxn = env.write_txn().unwrap();
+        index_word(&index, &mut wtxn, "a", 0, 1);
+        index_word(&index, &mut wtxn, "b", 1, 1);
+        index_word(&index, &mut wtxn, "c", 2, 1);
+        wtxn.commit().unwrap();
+
+        let rtxn = env.read_txn().unwrap();
+        let phrase = Phrase::new(vec!["a".into(), "b".into(), "c".into()]);
+        let docids = index.phrase_documents(&rtxn, &phrase).unwrap();
+        assert_eq!(docids.iter().collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn proximity_documents_reports_the_minimum_window_across_occurrences() {
+        let (_dir, env, index) = temp_index();
+        let mut wtxn = env.write_txn().unwrap();
+        // A far-apart occurrence (window 5) ...
+        index_word(&index, &mut wtxn, "a", 0, 1);
+        index_word(&index, &mut wtxn, "b", 5, 1);
+        // ... and a second, closer occurrence (window 1) of the same two words in the
+        // same document.
+        index_word(&index, &mut wtxn, "a", 10, 1);
+        index_word(&index, &mut wtxn, "b", 11, 1);
+        wtxn.commit().unwrap();
+
+        let rtxn = env.read_txn().unwrap();
+        let words = vec!["a".to_owned(), "b".to_owned()];
+        let matches = index.proximity_documents(&rtxn, &words, 5).unwrap();
+        assert_eq!(matches.get(&1), Some(&1));
+    }
+
+    #[test]
+    fn proximity_documents_respects_max_window() {
+        let (_dir, env, index) = temp_index();
+        let mut wtxn = env.write_txn().unwrap();
+        index_word(&index, &mut wtxn, "a", 0, 1);
+        index_word(&index, &mut wtxn, "b", 5, 1);
+        wtxn.commit().unwrap();
+
+        let rtxn = env.read_txn().unwrap();
+        let words = vec!["a".to_owned(), "b".to_owned()];
+        let matches = index.proximity_documents(&rtxn, &words, 2).unwrap();
+        assert!(matches.is_empty());
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::BTreeMap;
+
+use roaring::RoaringBitmap;
+
+use crate::criterion::{default_criteria, CriterionScores};
+use crate::{heed_codec::RoaringBitmapCodec, DocumentId, FieldId, FilterCondition, Index, Phrase, DOCUMENTS_IDS_KEY};
+
+/// The extra slack (beyond exact adjacency) allowed when scoring an unquoted multi-word
+/// query's word proximity, matching the granularity `word_four_positions_docids` already
+/// groups positions into — looser than that and the coarse pre-filter stops helping.
+const LOOSE_PROXIMITY_SLACK: u32 = 4;
+
+/// A search builder over an [`Index`]: configure it with `query`, `phrase`, `filter`,
+/// `facet_distribution`, `offset` and `limit`, then call [`Search::execute`].
+pub struct Search<'a> {
+    rtxn: &'a heed::RoTxn<'a>,
+    index: &'a Index,
+    query: Option<String>,
+    phrase: Option<Phrase>,
+    filter: Option<FilterCondition>,
+    facet_distribution: Vec<FieldId>,
+    offset: usize,
+    limit: usize,
+}
+
+impl<'a> Search<'a> {
+    pub fn new(rtxn: &'a heed::RoTxn<'a>, index: &'a Index) -> Search<'a> {
+        Search {
+            rtxn,
+            index,
+            query: None,
+            phrase: None,
+            filter: None,
+            facet_distribution: Vec::new(),
+            offset: 0,
+            limit: 20,
+        }
+    }
+
+    pub fn query(&mut self, query: impl Into<String>) -> &mut Search<'a> {
+        self.query = Some(query.into());
+        self
+    }
+
+    /// Requires the documents to additionally contain `phrase`'s words adjacent and in
+    /// order (e.g. a quoted `"new york"` query), intersecting the candidates from `query`
+    /// and feeding the match's proximity window into the
+    /// [`Proximity`](crate::criterion::Proximity) criterion.
+    pub fn phrase(&mut self, phrase: Phrase) -> &mut Search<'a> {
+        self.phrase = Some(phrase);
+        self
+    }
+
+    /// Restricts the documents to those matching `condition` (e.g. `price < 50`),
+    /// intersecting it with the candidates from `query`/`phrase`.
+    pub fn filter(&mut self, condition: FilterCondition) -> &mut Search<'a> {
+        self.filter = Some(condition);
+        self
+    }
+
+    /// Requests a value → matching-document-count distribution for `field_id` to be
+    /// computed over the final candidate set and returned in
+    /// [`SearchResult::facet_distribution`].
+    pub fn facet_distribution(&mut self, field_id: FieldId) -> &mut Search<'a> {
+        self.facet_distribution.push(field_id);
+        self
+    }
+
+    pub fn offset(&mut self, offset: usize) -> &mut Search<'a> {
+        self.offset = offset;
+        self
+    }
+
+    pub fn limit(&mut self, limit: usize) -> &mut Search<'a> {
+        self.limit = limit;
+        self
+    }
+
+    /// Resolves the query into a candidate bitmap: every whitespace-separated word is
+    /// resolved to the documents containing it (its exact `word_attribute_docids` postings,
+    /// or the union of its fuzzy matches' postings when it has no exact match — see
+    /// [`Index::fuzzy_matching_words`] — recording their edit distance into `scores` for the
+    /// [`Typo`](crate::criterion::Typo) criterion to rank by), and the query's candidates are
+    /// the *intersection* of every word's documents, i.e. all words must appear.
+    fn resolve_query(&self, scores: &mut CriterionScores) -> anyhow::Result<Option<RoaringBitmap>> {
+        let query = match &self.query {
+            Some(query) => query,
+            None => return Ok(None),
+        };
+
+        let mut candidates: Option<RoaringBitmap> = None;
+        for word in query.split_whitespace() {
+            let exact = self.index.word_documents(self.rtxn, word)?;
+
+            let word_docids = if !exact.is_empty() {
+                exact
+            } else {
+                let mut matched = RoaringBitmap::new();
+                for (_fuzzy_word, distance, docids) in self.index.fuzzy_matching_words(self.rtxn, word)? {
+                    for docid in &docids {
+                        scores.typo.entry(docid)
+                            .and_modify(|best| *best = (*best).min(distance))
+                            .or_insert(distance);
+                    }
+                    matched |= docids;
+                }
+                matched
+            };
+
+            candidates = Some(match candidates {
+                Some(candidates) => candidates & word_docids,
+                None => word_docids,
+            });
+        }
+
+        Ok(Some(candidates.unwrap_or_default()))
+    }
+
+    /// Records a proximity ranking signal for an unquoted, multi-word `query` by looking for
+    /// its words within a loose window (see [`LOOSE_PROXIMITY_SLACK`]), without filtering the
+    /// candidates by it — unlike [`Search::apply_phrase`], which requires strict adjacency.
+    /// A no-op for single-word queries, which have nothing to be proximate to.
+    fn score_query_proximity(&self, words: &[String], scores: &mut CriterionScores) -> anyhow::Result<()> {
+        if words.len() < 2 {
+            return Ok(());
+        }
+
+        let window = words.len() as u32 - 1 + LOOSE_PROXIMITY_SLACK;
+        let matches = self.index.proximity_documents(self.rtxn, words, window)?;
+        for (&docid, &distance) in &matches {
+            scores.proximity.entry(docid)
+                .and_modify(|best| *best = (*best).min(distance))
+                .or_insert(distance);
+        }
+
+        Ok(())
+    }
+
+    /// Intersects `candidates` with the documents matching `self.phrase`, if any, recording
+    /// each match's proximity window into `scores` for the [`Proximity`](crate::criterion::Proximity)
+    /// criterion to rank by.
+    fn apply_phrase(&self, candidates: RoaringBitmap, scores: &mut CriterionScores) -> anyhow::Result<RoaringBitmap> {
+        let phrase = match &self.phrase {
+            Some(phrase) => phrase,
+            None => return Ok(candidates),
+        };
+
+        // A single-word "phrase" has no adjacency to score: fall back to a normal word
+        // match, same as Index::phrase_documents.
+        let phrase_docids = if phrase.words.len() <= 1 {
+            match phrase.words.first() {
+                Some(word) => self.index.word_documents(self.rtxn, word)?,
+                None => RoaringBitmap::new(),
+            }
+        } else {
+            let window = phrase.words.len() as u32 - 1;
+            let matches = self.index.proximity_documents(self.rtxn, &phrase.words, window)?;
+            for (&docid, &distance) in &matches {
+                scores.proximity.entry(docid)
+                    .and_modify(|best| *best = (*best).min(distance))
+                    .or_insert(distance);
+            }
+            matches.keys().copied().collect()
+        };
+
+        Ok(candidates & phrase_docids)
+    }
+
+    pub fn execute(&self) -> anyhow::Result<SearchResult> {
+        let mut scores = CriterionScores::default();
+        let candidates = match self.resolve_query(&mut scores)? {
+            Some(candidates) => candidates,
+            None => self.index.main
+                .get::<_, heed::types::Str, RoaringBitmapCodec>(self.rtxn, DOCUMENTS_IDS_KEY)?
+                .unwrap_or_default(),
+        };
+        if self.phrase.is_none() {
+            if let Some(query) = &self.query {
+                let words: Vec<String> = query.split_whitespace().map(str::to_owned).collect();
+                self.score_query_proximity(&words, &mut scores)?;
+            }
+        }
+        let candidates = self.apply_phrase(candidates, &mut scores)?;
+        let candidates = match &self.filter {
+            Some(filter) => candidates & filter.resolve(self.rtxn, self.index)?,
+            None => candidates,
+        };
+
+        let mut documents_ids: Vec<DocumentId> = candidates.iter().collect();
+        let criteria = default_criteria();
+        documents_ids.sort_by(|&lhs, &rhs| {
+            criteria.iter()
+                .map(|criterion| criterion.evaluate(&scores, lhs, rhs))
+                .find(|ordering| *ordering != std::cmp::Ordering::Equal)
+                .unwrap_or_else(|| lhs.cmp(&rhs))
+        });
+
+        let mut facet_distribution = BTreeMap::new();
+        for &field_id in &self.facet_distribution {
+            let distribution = self.index.facet_string_distribution(self.rtxn, field_id, &candidates)?;
+            facet_distribution.insert(field_id, distribution);
+        }
+
+        let number_of_candidates = documents_ids.len();
+        let documents_ids = documents_ids.into_iter().skip(self.offset).take(self.limit).collect();
+
+        Ok(SearchResult { documents_ids, number_of_candidates, facet_distribution })
+    }
+}
+
+/// The outcome of running a [`Search`]: the page of matching document ids, ranked by the
+/// default [`Criterion`](crate::Criterion) chain, the total number of candidates before
+/// pagination, and (for every field passed to [`Search::facet_distribution`]) the count of
+/// matching documents per facet value.
+pub struct SearchResult {
+    pub documents_ids: Vec<DocumentId>,
+    pub number_of_candidates: usize,
+    pub facet_distribution: BTreeMap<FieldId, BTreeMap<String, u64>>,
+}